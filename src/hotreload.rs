@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+use vulkano as vk;
+
+type Result<T> = std::result::Result<T, anyhow::Error>;
+
+/// Scene configuration that can be hot-reloaded from disk: clear color, present mode and the
+/// shader sources the `GraphicsPipeline` is rebuilt from.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Config {
+    pub clear_color: [f32; 4],
+    #[serde(default)]
+    pub present_mode: PresentModeConfig,
+    pub shaders: ShaderPaths,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct ShaderPaths {
+    pub vertex: PathBuf,
+    pub fragment: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+pub enum PresentModeConfig {
+    #[default]
+    Fifo,
+    FifoRelaxed,
+    Mailbox,
+    Immediate,
+}
+
+impl From<PresentModeConfig> for vk::swapchain::PresentMode {
+    fn from(mode: PresentModeConfig) -> Self {
+        match mode {
+            PresentModeConfig::Fifo => vk::swapchain::PresentMode::Fifo,
+            PresentModeConfig::FifoRelaxed => vk::swapchain::PresentMode::FifoRelaxed,
+            PresentModeConfig::Mailbox => vk::swapchain::PresentMode::Mailbox,
+            PresentModeConfig::Immediate => vk::swapchain::PresentMode::Immediate,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        ron::from_str(&contents).map_err(Into::into)
+    }
+}
+
+/// Compiles a GLSL source file into a `ShaderModule` at runtime, so it can be redone whenever
+/// the file on disk changes without restarting the process.
+pub fn compile_shader(device: &Arc<vk::device::Device>, path: &Path, kind: shaderc::ShaderKind) -> Result<Arc<vk::shader::ShaderModule>> {
+    let source = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("failed to read shader `{}`: {e}", path.display()))?;
+    let compiler = shaderc::Compiler::new().ok_or_else(|| anyhow::anyhow!("failed to create shaderc compiler"))?;
+    let artifact = compiler.compile_into_spirv(&source, kind, &path.to_string_lossy(), "main", None)?;
+    unsafe { vk::shader::ShaderModule::from_words(device.clone(), artifact.as_binary()) }.map_err(Into::into)
+}
+
+/// What changed on disk and needs to be applied on the render thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReloadEvent {
+    Shaders,
+    Config,
+}
+
+/// Watches a shader directory and a config file on a background thread, debouncing bursts of
+/// filesystem events on each path into one `ReloadEvent` per changed path, delivered over a
+/// channel so GPU work stays on the render thread driving `event_loop`.
+pub struct HotReloadWatcher {
+    _debouncer: notify_debouncer_mini::Debouncer<notify::RecommendedWatcher>,
+    events: mpsc::Receiver<ReloadEvent>,
+}
+
+impl HotReloadWatcher {
+    pub fn new(shader_dir: &Path, config_path: &Path) -> Result<Self> {
+        use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
+        let (tx, rx) = mpsc::channel();
+        // The watcher reports absolute paths (relative watch targets are joined onto the
+        // current directory), so compare events against the same absolute forms.
+        let cwd = std::env::current_dir()?;
+        let watched_shader_dir = cwd.join(shader_dir);
+        let watched_config_path = cwd.join(config_path);
+        let mut debouncer = new_debouncer(Duration::from_millis(200), move |result: DebounceEventResult| {
+            let events = match result {
+                Ok(events) => events,
+                Err(e) => {
+                    log::warn!("hot-reload watcher error: {e}");
+                    return;
+                }
+            };
+            for event in events {
+                let reload = if event.path.starts_with(&watched_shader_dir) {
+                    ReloadEvent::Shaders
+                } else if event.path == watched_config_path {
+                    ReloadEvent::Config
+                } else {
+                    continue;
+                };
+                if tx.send(reload).is_err() {
+                    return;
+                }
+            }
+        })?;
+        debouncer.watcher().watch(shader_dir, notify::RecursiveMode::Recursive)?;
+        // `Path::parent()` returns `Some("")` for a bare root-level file name like "config.ron",
+        // and an empty path isn't a valid watch target, so treat it as the current directory.
+        let config_dir = match config_path.parent() {
+            Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+            Some(dir) => dir,
+            None => Path::new("."),
+        };
+        if config_dir != shader_dir {
+            debouncer.watcher().watch(config_dir, notify::RecursiveMode::NonRecursive)?;
+        }
+        Ok(Self { _debouncer: debouncer, events: rx })
+    }
+    /// Drains pending reload requests without blocking the render thread.
+    pub fn poll(&self) -> impl Iterator<Item = ReloadEvent> + '_ {
+        self.events.try_iter()
+    }
+}
@@ -1,41 +1,83 @@
 // mod vulkan;
 mod vkenv;
 mod render;
-use std::sync::Arc;
+mod hotreload;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 use vulkano as vk;
-use winit::event::{Event, WindowEvent};
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 
 // pub use vulkan::*;
 
+const SHADER_DIR: &str = "shaders";
+const CONFIG_PATH: &str = "config.ron";
+
 fn main() {
+    env_logger::init();
+
     let event_loop = EventLoop::new(); // ignore this for now
                                        //
     let vkenv = Arc::new(vkenv::VulkanEnvironment::new(&event_loop).expect("failed to create environment"));
 
-    let mut swapchain = render::Swapchain::new(vkenv.clone())
+    let mut config = hotreload::Config::load(Path::new(CONFIG_PATH)).expect("failed to load config");
+
+    let mut swapchain = render::Swapchain::new(vkenv.clone(), true, config.present_mode.into())
         .expect("failed to create swapchain");
-    swapchain.framebuffers
-        .build_command_buffer(|builder, render_begin_info| {
-            builder
-                .begin_render_pass(
-                    vk::command_buffer::RenderPassBeginInfo {
-                        clear_values: vec![Some([0.5, 0.1, 0.1, 1.0].into())],
-                        ..render_begin_info
-                    },
-                    vk::command_buffer::SubpassContents::Inline,
-                )
-                .map_err(Into::<anyhow::Error>::into)?
-                // .bind_pipeline_graphics(pipeline.clone())
-                // .bind_vertex_buffers(0, vertex_buffer.clone())
-                // .draw(vertex_buffer.len() as u32, 1, 0, 0)
-                // .unwrap()
-                .end_render_pass()
-                .map_err(Into::<anyhow::Error>::into)?;
-            Ok(())
-        })
-        .expect("failed to build command buffer");
+
+    let clear_color = Arc::new(Mutex::new(config.clear_color));
+
+    let vs = hotreload::compile_shader(&vkenv.device, &config.shaders.vertex, shaderc::ShaderKind::Vertex)
+        .expect("failed to compile vertex shader");
+    let fs = hotreload::compile_shader(&vkenv.device, &config.shaders.fragment, shaderc::ShaderKind::Fragment)
+        .expect("failed to compile fragment shader");
+    let pipeline = Arc::new(Mutex::new(
+        render::GraphicsPipeline::new::<render::Vertex>(vkenv.clone(), vs, fs, swapchain.render_pass.clone(), swapchain.has_depth(), vkenv.dimension())
+            .expect("failed to build graphics pipeline"),
+    ));
+    swapchain.register_pipeline(pipeline.clone());
+
+    let mesh = render::Mesh::new(
+        &vkenv,
+        &[
+            render::Vertex { position: [-0.5, -0.5, 0.0] },
+            render::Vertex { position: [0.5, -0.5, 0.0] },
+            render::Vertex { position: [0.0, 0.5, 0.0] },
+        ],
+    )
+    .expect("failed to build mesh");
+
+    let vertex_buffer = mesh.vertex_buffer.clone();
+    {
+        let pipeline = pipeline.clone();
+        let clear_color = clear_color.clone();
+        swapchain.framebuffers
+            .build_command_buffer(move |builder, render_begin_info| {
+                let graphics_pipeline = pipeline.lock().unwrap().pipeline.clone();
+                let clear_color = *clear_color.lock().unwrap();
+                builder
+                    .begin_render_pass(
+                        vk::command_buffer::RenderPassBeginInfo {
+                            clear_values: vec![Some(clear_color.into()), Some(vk::format::ClearValue::Depth(1.0))],
+                            ..render_begin_info
+                        },
+                        vk::command_buffer::SubpassContents::Inline,
+                    )
+                    .map_err(Into::<anyhow::Error>::into)?
+                    .bind_pipeline_graphics(graphics_pipeline)
+                    .bind_vertex_buffers(0, vertex_buffer.clone())
+                    .draw(vertex_buffer.len() as u32, 1, 0, 0)
+                    .map_err(Into::<anyhow::Error>::into)?
+                    .end_render_pass()
+                    .map_err(Into::<anyhow::Error>::into)?;
+                Ok(())
+            })
+            .expect("failed to build command buffer");
+    }
+
+    let watcher = hotreload::HotReloadWatcher::new(Path::new(SHADER_DIR), Path::new(CONFIG_PATH))
+        .expect("failed to start hot-reload watcher");
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
@@ -50,10 +92,77 @@ fn main() {
         } => {
             swapchain.recreate();
         }
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::V),
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => {
+            // Toggle between vsync (Fifo) and low-latency (Mailbox) presentation.
+            let next_mode = if swapchain.present_mode() == vk::swapchain::PresentMode::Fifo {
+                vk::swapchain::PresentMode::Mailbox
+            } else {
+                vk::swapchain::PresentMode::Fifo
+            };
+            if let Err(e) = swapchain.set_present_mode(next_mode) {
+                log::warn!("failed to switch present mode: {e}");
+            }
+        }
         Event::MainEventsCleared => {
+            for reload in watcher.poll().collect::<Vec<_>>() {
+                match reload {
+                    hotreload::ReloadEvent::Config => match hotreload::Config::load(Path::new(CONFIG_PATH)) {
+                        Ok(new_config) => {
+                            *clear_color.lock().unwrap() = new_config.clear_color;
+                            if let Err(e) = swapchain.set_present_mode(new_config.present_mode.into()) {
+                                log::warn!("failed to apply reloaded present mode: {e}");
+                            }
+                            let shaders_changed = new_config.shaders != config.shaders;
+                            config = new_config;
+                            if shaders_changed {
+                                let shaders = hotreload::compile_shader(&vkenv.device, &config.shaders.vertex, shaderc::ShaderKind::Vertex)
+                                    .and_then(|vs| {
+                                        let fs = hotreload::compile_shader(&vkenv.device, &config.shaders.fragment, shaderc::ShaderKind::Fragment)?;
+                                        render::GraphicsPipeline::new::<render::Vertex>(vkenv.clone(), vs, fs, swapchain.render_pass.clone(), swapchain.has_depth(), vkenv.dimension())
+                                    });
+                                match shaders {
+                                    Ok(new_pipeline) => *pipeline.lock().unwrap() = new_pipeline,
+                                    Err(e) => log::warn!("failed to reload shaders after config change: {e}"),
+                                }
+                            }
+                            if let Err(e) = swapchain.framebuffers.update_command_buffer() {
+                                log::warn!("failed to apply reloaded config: {e}");
+                            }
+                        }
+                        Err(e) => log::warn!("failed to reload config: {e}"),
+                    },
+                    hotreload::ReloadEvent::Shaders => {
+                        let shaders = hotreload::compile_shader(&vkenv.device, &config.shaders.vertex, shaderc::ShaderKind::Vertex)
+                            .and_then(|vs| {
+                                let fs = hotreload::compile_shader(&vkenv.device, &config.shaders.fragment, shaderc::ShaderKind::Fragment)?;
+                                render::GraphicsPipeline::new::<render::Vertex>(vkenv.clone(), vs, fs, swapchain.render_pass.clone(), swapchain.has_depth(), vkenv.dimension())
+                            });
+                        match shaders {
+                            Ok(new_pipeline) => {
+                                *pipeline.lock().unwrap() = new_pipeline;
+                                if let Err(e) = swapchain.framebuffers.update_command_buffer() {
+                                    log::warn!("failed to apply reloaded shaders: {e}");
+                                }
+                            }
+                            Err(e) => log::warn!("failed to reload shaders: {e}"),
+                        }
+                    }
+                }
+            }
             swapchain.draw().expect("failed to draw");
         }
         _ => (),
     });
 }
-
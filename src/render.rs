@@ -4,47 +4,69 @@ use crate::vkenv::VulkanEnvironment;
 
 type Result<T> = std::result::Result<T, anyhow::Error>;
 
+/// Type-erases a `FenceSignalFuture<F>`, whose concrete `F` differs at every call site, behind
+/// a single boxed wait closure so a frame-in-flight slot can hold one regardless of how its
+/// future was built.
 struct Fence {
-    into_boxed_closure: Option<Box<dyn FnOnce() -> Box<dyn vk::sync::GpuFuture>>>,
     wait_closure: Option<Box<dyn FnOnce() -> std::result::Result<(), vulkano::sync::FlushError>>>
 }
 impl Fence {
-    fn get_boxed(&mut self) -> Box<dyn vk::sync::GpuFuture> {
-        let opt = self.into_boxed_closure.take().map(|v| v());
-        opt.unwrap()
+    fn new<F: vk::sync::GpuFuture + 'static>(future: vk::sync::future::FenceSignalFuture<F>) -> Self {
+        let future = Arc::new(future);
+        Fence {
+            wait_closure: Some(Box::new(move || future.wait(None))),
+        }
     }
     fn wait(&mut self) -> std::result::Result<(), vulkano::sync::FlushError> {
-        let opt = self.wait_closure.take().map(|v| v());
-        opt.unwrap()
+        match self.wait_closure.take() {
+            Some(wait) => wait(),
+            None => Ok(()),
+        }
     }
 }
 
 pub struct Framebuffer {
     vkenv: Arc<VulkanEnvironment>,
     pub image: Arc<vk::image::swapchain::SwapchainImage>,
+    pub depth_image: Option<Arc<vk::image::view::ImageView<vk::image::AttachmentImage>>>,
     pub framebuffer: Arc<vk::render_pass::Framebuffer>,
     pub command_buffer: Option<Arc<vk::command_buffer::PrimaryAutoCommandBuffer>>,
-    fence: Option<Fence>
 }
 
 type AutoCommandBufferBuilder = vk::command_buffer::AutoCommandBufferBuilder<vk::command_buffer::PrimaryAutoCommandBuffer<<vk::command_buffer::allocator::StandardCommandBufferAllocator as vk::command_buffer::allocator::CommandBufferAllocator>::Alloc>, vk::command_buffer::allocator::StandardCommandBufferAllocator>;
 
 impl Framebuffer {
-    fn new(vkenv: Arc<VulkanEnvironment>, render_pass: Arc<vk::render_pass::RenderPass>, image: Arc<vk::image::swapchain::SwapchainImage>) -> Result<Self> {
-        let image_view = vk::image::view::ImageView::new_default(image.clone())?;
+    fn new(
+        vkenv: Arc<VulkanEnvironment>,
+        render_pass: Arc<vk::render_pass::RenderPass>,
+        image: Arc<vk::image::swapchain::SwapchainImage>,
+        depth_format: Option<vk::format::Format>,
+    ) -> Result<Self> {
+        let extent = image.dimensions().width_height();
+        let color_view = vk::image::view::ImageView::new_default(image.clone())?;
+        let depth_image = depth_format
+            .map(|format| -> Result<_> {
+                let image = vk::image::AttachmentImage::transient(&vkenv.memory_allocator, extent, format)?;
+                Ok(vk::image::view::ImageView::new_default(image)?)
+            })
+            .transpose()?;
+        let mut attachments = vec![color_view];
+        if let Some(depth_view) = &depth_image {
+            attachments.push(depth_view.clone());
+        }
         let fb = vk::render_pass::Framebuffer::new(
             render_pass,
             vk::render_pass::FramebufferCreateInfo {
-                attachments: vec![image_view],
+                attachments,
                 ..Default::default()
             },
         )?;
         Ok(Self {
             vkenv,
             image,
+            depth_image,
             framebuffer: fb,
             command_buffer: None,
-            fence: None
         })
     }
     pub fn build_command_buffer(&mut self, build_callback: &dyn Fn(&mut AutoCommandBufferBuilder, vk::command_buffer::RenderPassBeginInfo) -> Result<()>,) -> Result<()> 
@@ -75,10 +97,15 @@ pub struct Framebuffers {
 }
 
 impl Framebuffers {
-    pub fn new(vkenv: &Arc<VulkanEnvironment>, images: Vec<Arc<vk::image::swapchain::SwapchainImage>>, render_pass: &Arc<vk::render_pass::RenderPass>) -> Result<Self> {
+    pub fn new(
+        vkenv: &Arc<VulkanEnvironment>,
+        images: Vec<Arc<vk::image::swapchain::SwapchainImage>>,
+        render_pass: &Arc<vk::render_pass::RenderPass>,
+        depth_format: Option<vk::format::Format>,
+    ) -> Result<Self> {
         let framebuffers = images
             .into_iter()
-            .map(|image| Framebuffer::new(vkenv.clone(), render_pass.clone(), image))
+            .map(|image| Framebuffer::new(vkenv.clone(), render_pass.clone(), image, depth_format))
             .collect::<Result<Vec<_>>>()?;
         Ok(Self {
             list: framebuffers,
@@ -103,6 +130,9 @@ impl Framebuffers {
         }
         Ok(())
     }
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
 }
 
 impl std::ops::Index<usize> for Framebuffers {
@@ -116,75 +146,322 @@ impl std::ops::IndexMut<usize> for Framebuffers {
         &mut self.list[index]
     }
 }
+/// The only vertex format the crate currently draws with: a bare 3D position.
+#[derive(vk::buffer::BufferContents, vk::pipeline::graphics::vertex_input::Vertex, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct Vertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub position: [f32; 3],
+}
+
+/// A typed vertex buffer, with an optional index buffer, uploaded through the shared
+/// `VulkanEnvironment::memory_allocator`.
+pub struct Mesh<V: vk::buffer::BufferContents + Copy> {
+    pub vertex_buffer: vk::buffer::Subbuffer<[V]>,
+    pub index_buffer: Option<vk::buffer::Subbuffer<[u32]>>,
+}
+
+impl<V: vk::buffer::BufferContents + Copy> Mesh<V> {
+    pub fn new(vkenv: &VulkanEnvironment, vertices: &[V]) -> Result<Self> {
+        let vertex_buffer = upload_device_local(vkenv, vk::buffer::BufferUsage::VERTEX_BUFFER, vertices)?;
+        Ok(Self { vertex_buffer, index_buffer: None })
+    }
+    pub fn with_indices(mut self, vkenv: &VulkanEnvironment, indices: &[u32]) -> Result<Self> {
+        let index_buffer = upload_device_local(vkenv, vk::buffer::BufferUsage::INDEX_BUFFER, indices)?;
+        self.index_buffer = Some(index_buffer);
+        Ok(self)
+    }
+}
+
+/// Uploads `data` into a device-local (`GpuOnly`) buffer via a host-visible staging buffer and a
+/// one-off transfer command buffer, rather than leaving vertex/index data in host-visible memory
+/// the GPU would otherwise have to read across the PCIe bus on every draw.
+fn upload_device_local<T: vk::buffer::BufferContents + Copy>(
+    vkenv: &VulkanEnvironment,
+    usage: vk::buffer::BufferUsage,
+    data: &[T],
+) -> Result<vk::buffer::Subbuffer<[T]>> {
+    use vk::sync::GpuFuture;
+    let staging_buffer = vk::buffer::Buffer::from_iter(
+        &vkenv.memory_allocator,
+        vk::buffer::BufferCreateInfo {
+            usage: vk::buffer::BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        vk::memory::allocator::AllocationCreateInfo {
+            usage: vk::memory::allocator::MemoryUsage::Upload,
+            ..Default::default()
+        },
+        data.iter().copied(),
+    )?;
+    let device_buffer = vk::buffer::Buffer::new_slice::<T>(
+        &vkenv.memory_allocator,
+        vk::buffer::BufferCreateInfo {
+            usage: usage | vk::buffer::BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        vk::memory::allocator::AllocationCreateInfo {
+            usage: vk::memory::allocator::MemoryUsage::GpuOnly,
+            ..Default::default()
+        },
+        data.len() as u64,
+    )?;
+    let queue = vkenv.queues.graphics.clone();
+    let mut builder = vk::command_buffer::AutoCommandBufferBuilder::primary(
+        &vkenv.command_buffer_allocator,
+        queue.queue_family_index(),
+        vk::command_buffer::CommandBufferUsage::OneTimeSubmit,
+    )?;
+    builder.copy_buffer(vk::command_buffer::CopyBufferInfo::buffers(staging_buffer, device_buffer.clone()))?;
+    let command_buffer = builder.build()?;
+    vk::sync::now(vkenv.device.clone())
+        .then_execute(queue, command_buffer)?
+        .then_signal_fence_and_flush()?
+        .wait(None)?;
+    Ok(device_buffer)
+}
+
+/// A graphics pipeline built from a vertex/fragment SPIR-V pair against a render pass's first
+/// subpass. `rebuild` re-derives the viewport from a new extent; call it from
+/// `Swapchain::recreate` (via `Swapchain::register_pipeline`) so resizing keeps drawing correctly.
+pub struct GraphicsPipeline {
+    vkenv: Arc<VulkanEnvironment>,
+    vs: Arc<vk::shader::ShaderModule>,
+    fs: Arc<vk::shader::ShaderModule>,
+    vertex_input_state: vk::pipeline::graphics::vertex_input::VertexInputState,
+    render_pass: Arc<vk::render_pass::RenderPass>,
+    /// Whether `render_pass`'s subpass 0 carries a depth/stencil attachment; must match the
+    /// render pass exactly, or `.build` fails with a depth-stencil/render-pass mismatch.
+    depth: bool,
+    pub pipeline: Arc<vk::pipeline::graphics::GraphicsPipeline>,
+}
+
+impl GraphicsPipeline {
+    /// `depth` must agree with whether `render_pass`'s subpass 0 has a depth/stencil attachment
+    /// (see `Swapchain::has_depth`); a pipeline built with `depth: true` against a depth-less
+    /// render pass (or vice versa) fails to build.
+    pub fn new<V: vk::pipeline::graphics::vertex_input::Vertex>(
+        vkenv: Arc<VulkanEnvironment>,
+        vs: Arc<vk::shader::ShaderModule>,
+        fs: Arc<vk::shader::ShaderModule>,
+        render_pass: Arc<vk::render_pass::RenderPass>,
+        depth: bool,
+        extent: [u32; 2],
+    ) -> Result<Self> {
+        let vertex_input_state = V::per_vertex();
+        let pipeline = Self::build(&vkenv, &vs, &fs, vertex_input_state.clone(), &render_pass, depth, extent)?;
+        Ok(Self { vkenv, vs, fs, vertex_input_state, render_pass, depth, pipeline })
+    }
+    pub fn rebuild(&mut self, extent: [u32; 2]) -> Result<()> {
+        self.pipeline = Self::build(&self.vkenv, &self.vs, &self.fs, self.vertex_input_state.clone(), &self.render_pass, self.depth, extent)?;
+        Ok(())
+    }
+    fn build(
+        vkenv: &Arc<VulkanEnvironment>,
+        vs: &Arc<vk::shader::ShaderModule>,
+        fs: &Arc<vk::shader::ShaderModule>,
+        vertex_input_state: vk::pipeline::graphics::vertex_input::VertexInputState,
+        render_pass: &Arc<vk::render_pass::RenderPass>,
+        depth: bool,
+        extent: [u32; 2],
+    ) -> Result<Arc<vk::pipeline::graphics::GraphicsPipeline>> {
+        let vs_entry = vs.entry_point("main").ok_or_else(|| anyhow::anyhow!("vertex shader has no `main` entry point"))?;
+        let fs_entry = fs.entry_point("main").ok_or_else(|| anyhow::anyhow!("fragment shader has no `main` entry point"))?;
+        let viewport = vk::pipeline::graphics::viewport::Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [extent[0] as f32, extent[1] as f32],
+            depth_range: 0.0..1.0,
+        };
+        let subpass = vk::render_pass::Subpass::from(render_pass.clone(), 0).ok_or_else(|| anyhow::anyhow!("render pass has no subpass 0"))?;
+        let mut builder = vk::pipeline::graphics::GraphicsPipeline::start()
+            .vertex_input_state(vertex_input_state)
+            .vertex_shader(vs_entry, ())
+            .input_assembly_state(vk::pipeline::graphics::input_assembly::InputAssemblyState::new())
+            .viewport_state(vk::pipeline::graphics::viewport::ViewportState::viewport_fixed_scissor_irrelevant([viewport]))
+            .fragment_shader(fs_entry, ());
+        if depth {
+            builder = builder.depth_stencil_state(vk::pipeline::graphics::depth_stencil::DepthStencilState::simple_depth_test());
+        }
+        builder
+            .render_pass(subpass)
+            .build(vkenv.device.clone())
+            .map_err(Into::into)
+    }
+}
+
+/// A one-off or repeatable GPU compute dispatch built from a SPIR-V entry point.
+///
+/// Bindings (storage buffers/images) are wired up once at construction through a descriptor
+/// set allocated from `VulkanEnvironment::descriptor_set_allocator`; `dispatch` then records
+/// and submits the work on the compute queue.
+pub struct ComputeTask {
+    vkenv: Arc<VulkanEnvironment>,
+    pipeline: Arc<vk::pipeline::ComputePipeline>,
+    descriptor_set: Arc<vk::descriptor_set::PersistentDescriptorSet>,
+}
+
+impl ComputeTask {
+    pub fn new(
+        vkenv: Arc<VulkanEnvironment>,
+        shader: Arc<vk::shader::ShaderModule>,
+        entry_point: &str,
+        bindings: impl IntoIterator<Item = vk::descriptor_set::WriteDescriptorSet>,
+    ) -> Result<Self> {
+        let entry_point = shader
+            .entry_point(entry_point)
+            .ok_or_else(|| anyhow::anyhow!("shader entry point `{entry_point}` not found"))?;
+        let pipeline = vk::pipeline::ComputePipeline::new(vkenv.device.clone(), entry_point, &(), None, |_| {})?;
+        let layout = pipeline
+            .layout()
+            .set_layouts()
+            .get(0)
+            .ok_or_else(|| anyhow::anyhow!("compute pipeline has no descriptor set layout"))?;
+        let descriptor_set = vk::descriptor_set::PersistentDescriptorSet::new(&vkenv.descriptor_set_allocator, layout.clone(), bindings)?;
+        Ok(Self { vkenv, pipeline, descriptor_set })
+    }
+    /// Records and submits a single dispatch on the compute queue, returning the future the
+    /// caller should join with whatever GPU work depends on its result.
+    pub fn dispatch(&self, group_counts: [u32; 3]) -> Result<impl vk::sync::GpuFuture> {
+        use vk::sync::GpuFuture;
+        let queue = self.vkenv.queues.compute.clone().ok_or_else(|| anyhow::anyhow!("no compute queue available"))?;
+        let mut builder = vk::command_buffer::AutoCommandBufferBuilder::primary(
+            &self.vkenv.command_buffer_allocator,
+            queue.queue_family_index(),
+            vk::command_buffer::CommandBufferUsage::OneTimeSubmit,
+        )?;
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(
+                vk::pipeline::PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )
+            .dispatch(group_counts)?;
+        let command_buffer = builder.build()?;
+        vk::sync::now(self.vkenv.device.clone())
+            .then_execute(queue, command_buffer)?
+            .then_signal_fence_and_flush()
+            .map_err(Into::into)
+    }
+}
+
 pub struct Swapchain {
     vkenv: Arc<VulkanEnvironment>,
     pub swapchain: Arc<vk::swapchain::Swapchain>,
     pub render_pass: Arc<vk::render_pass::RenderPass>,
     pub framebuffers: Framebuffers,
-    previous_image_index: u32,
+    depth_format: Option<vk::format::Format>,
+    pipelines: Vec<Arc<std::sync::Mutex<GraphicsPipeline>>>,
+    /// One fence per swapchain image, indexed by the image index `acquire_next_image` returns
+    /// rather than by a rotating cursor: the `Mailbox` present mode can hand images back out of
+    /// order, so only the image's own previous submission may gate reusing its framebuffer's
+    /// command buffer. No rotating frame cursor is kept: with images coming back out of order,
+    /// the slot such a cursor points at need not match the acquired image, so its fence could
+    /// belong to a different image than the command buffer about to be reused.
+    images_in_flight: Vec<Option<Fence>>,
 }
 
 impl Swapchain {
-    pub fn new(vkenv: Arc<VulkanEnvironment>) -> Result<Self> {
+    /// `depth` enables a depth/stencil attachment alongside the color attachment on every
+    /// framebuffer; pass `false` for 2D-only rendering that has no use for depth testing.
+    /// `present_mode` is validated against the surface's supported modes and falls back to
+    /// `Fifo` (vsync) if unsupported; choosing `Mailbox` bumps the image count to at least 3
+    /// for proper triple buffering.
+    pub fn new(vkenv: Arc<VulkanEnvironment>, depth: bool, present_mode: vk::swapchain::PresentMode) -> Result<Self> {
         let caps = vkenv.surface_capabilities()?;
         let composite_alpha = caps.supported_composite_alpha
             .into_iter()
             .next()
             .ok_or_else(|| anyhow::anyhow!("no supported composite alpha"))?;
+        let present_mode = vkenv.validate_present_mode(present_mode)?;
+        let mut min_image_count = caps.min_image_count + 1; // How many buffers to use in the swapchain
+        if present_mode == vk::swapchain::PresentMode::Mailbox {
+            min_image_count = min_image_count.max(3);
+        }
         let (swapchain, images) = vk::swapchain::Swapchain::new(
                 vkenv.device.clone(),
                 vkenv.surface.clone(),
                 vk::swapchain::SwapchainCreateInfo {
-                    min_image_count: caps.min_image_count + 1, // How many buffers to use in the swapchain
+                    min_image_count,
                     image_format: vkenv.first_surface_format()?.map(|(f, _)| f),
                     image_extent: vkenv.dimension(),
                     image_usage: vk::image::ImageUsage::COLOR_ATTACHMENT, // What the images are going to be used for
                     composite_alpha,
+                    present_mode,
                     ..Default::default()
                 },
             )
             .map_err(|e| anyhow::anyhow!("failed to create swapchain: {}", e))?;
-        let render_pass = vkenv.new_render_pass(&swapchain)?;
-        let framebuffers = Framebuffers::new(&vkenv, images, &render_pass)?;
+        let depth_format = depth.then(|| vkenv.depth_format()).transpose()?;
+        let render_pass = vkenv.new_render_pass(&swapchain, depth_format.is_some())?;
+        let image_count = images.len();
+        let framebuffers = Framebuffers::new(&vkenv, images, &render_pass, depth_format)?;
         Ok(Self {
             vkenv,
             swapchain,
             render_pass,
             framebuffers,
-            previous_image_index: 0,
+            depth_format,
+            pipelines: Vec::new(),
+            images_in_flight: (0..image_count).map(|_| None).collect(),
         })
     }
+    /// Registers a pipeline so its viewport is rebuilt against the new extent on every
+    /// `recreate`, instead of going stale after a resize.
+    pub fn register_pipeline(&mut self, pipeline: Arc<std::sync::Mutex<GraphicsPipeline>>) {
+        self.pipelines.push(pipeline);
+    }
+    /// Whether `render_pass`'s subpass 0 has a depth/stencil attachment; any `GraphicsPipeline`
+    /// built against `render_pass` must pass the same value as its `depth` argument.
+    pub fn has_depth(&self) -> bool {
+        self.depth_format.is_some()
+    }
     pub fn recreate(&mut self) -> Result<()> {
-        let (swapchain, images) = self.swapchain.recreate(vk::swapchain::SwapchainCreateInfo {
-                image_extent: self.vkenv.dimension(),
-                ..self.swapchain.create_info() 
-            })
+        self.recreate_with(vk::swapchain::SwapchainCreateInfo {
+            image_extent: self.vkenv.dimension(),
+            ..self.swapchain.create_info()
+        })
+    }
+    /// Switches between vsync (`Fifo`) and low-latency (`Mailbox`/`Immediate`) presentation,
+    /// recreating the swapchain with the new mode. The chosen mode is preserved across any
+    /// later `recreate` since it reads back `self.swapchain.create_info()`.
+    pub fn set_present_mode(&mut self, present_mode: vk::swapchain::PresentMode) -> Result<()> {
+        let present_mode = self.vkenv.validate_present_mode(present_mode)?;
+        let mut create_info = self.swapchain.create_info();
+        create_info.image_extent = self.vkenv.dimension();
+        create_info.present_mode = present_mode;
+        if present_mode == vk::swapchain::PresentMode::Mailbox {
+            create_info.min_image_count = create_info.min_image_count.max(3);
+        }
+        self.recreate_with(create_info)
+    }
+    pub fn present_mode(&self) -> vk::swapchain::PresentMode {
+        self.swapchain.create_info().present_mode
+    }
+    fn recreate_with(&mut self, create_info: vk::swapchain::SwapchainCreateInfo) -> Result<()> {
+        let (swapchain, images) = self.swapchain.recreate(create_info)
             .map_err(|e| anyhow::anyhow!("failed to recreate swapchain: {}", e))?;
         self.swapchain = swapchain;
         let cb_builder = self.framebuffers.cb_builder.clone();
-        self.framebuffers = Framebuffers::new(&self.vkenv, images, &self.render_pass)?;
+        self.framebuffers = Framebuffers::new(&self.vkenv, images, &self.render_pass, self.depth_format)?;
         self.framebuffers.cb_builder = cb_builder;
+        let extent = self.vkenv.dimension();
+        for pipeline in &self.pipelines {
+            pipeline.lock().unwrap().rebuild(extent)?;
+        }
         self.framebuffers.update_command_buffer()?;
+        // The old fences were signalled against framebuffers/images that no longer exist.
+        self.images_in_flight = (0..self.framebuffers.len()).map(|_| None).collect();
         Ok(())
     }
     pub fn draw(&mut self) -> Result<()> {
         use vulkano::sync::future::GpuFuture;
         use vulkano::sync::FlushError;
+
         let recreate_swapchain = 'a: {
             let mut recreate_swapchain = false;
 
-            let previous_future = match &mut self.framebuffers[self.previous_image_index as usize].fence {
-                // Create a `NowFuture`.
-                None => {
-                    let mut now = vk::sync::now(self.vkenv.device.clone());
-                    now.cleanup_finished();
-            
-                    now.boxed()
-                }
-                // Use the existing `FenceSignalFuture`.
-                Some(fence) => fence.get_boxed(),
-            };
-            
             let (image_i, suboptimal, acquire_future) =
                 match vk::swapchain::acquire_next_image(self.swapchain.clone(), None) {
                     Ok(r) => r,
@@ -196,10 +473,17 @@ impl Swapchain {
             if suboptimal {
                 recreate_swapchain = true;
             }
-            if let Some(fence) = &mut self.framebuffers[image_i as usize].fence {
+
+            // Wait on this image's own previous submission before reusing its framebuffer's
+            // command buffer. Mailbox can return images out of order, so the image index from
+            // `acquire_next_image` is the only safe key here.
+            if let Some(fence) = &mut self.images_in_flight[image_i as usize] {
                 fence.wait()?;
             }
-            
+
+            let mut previous_future = vk::sync::now(self.vkenv.device.clone());
+            previous_future.cleanup_finished();
+
             let queue = self.vkenv.queues.graphics.clone();
             let command_buffer = self.framebuffers[image_i as usize].command_buffer.clone().ok_or_else(|| anyhow::anyhow!("no command buffer"))?;
             let future = previous_future
@@ -211,31 +495,19 @@ impl Swapchain {
                     vk::swapchain::SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_i),
                 )
                 .then_signal_fence_and_flush();
-            
+
             match future {
                 Ok(future) => {
-                    let fence1: Arc<vk::sync::future::FenceSignalFuture<_>> = Arc::new(future);
-                    let fence2: Arc<vk::sync::future::FenceSignalFuture<_>> = Arc::clone(&fence1);
-                    let new_fence = Fence {
-                        into_boxed_closure: Some(Box::new(move || {
-                            fence1.boxed()
-                        })),
-                        wait_closure: Some(Box::new(move || {
-                            fence2.wait(None)
-                        }))
-                    };
-                    
-                    self.framebuffers[image_i as usize].fence = Some(new_fence);
+                    self.images_in_flight[image_i as usize] = Some(Fence::new(future));
                 }
                 Err(FlushError::OutOfDate) => {
                     recreate_swapchain = true;
-                    self.framebuffers[image_i as usize].fence = None;
+                    self.images_in_flight[image_i as usize] = None;
                 }
                 Err(e) => {
                     return Err(anyhow::anyhow!("failed to flush future: {e}"));
                 }
             }
-            self.previous_image_index = image_i;
             recreate_swapchain
         };
         if recreate_swapchain {
@@ -3,12 +3,74 @@ use vulkano as vk;
 
 type Result<T> = std::result::Result<T, anyhow::Error>;
 
+/// Knobs that affect how the Vulkan instance and device are set up.
+///
+/// `validation` is also honoured through the `GL3D_VALIDATION` environment
+/// variable (any value, including empty, turns it on) so the layer can be
+/// enabled without touching call sites.
+#[derive(Debug, Clone, Copy)]
+pub struct VulkanOptions {
+    pub validation: bool,
+    /// Floor below which validation layer messages are dropped before reaching `log`. Only
+    /// meaningful when `validation` is enabled.
+    pub min_severity: DebugSeverity,
+}
+
+impl Default for VulkanOptions {
+    fn default() -> Self {
+        Self {
+            validation: std::env::var_os("GL3D_VALIDATION").is_some(),
+            min_severity: DebugSeverity::Warning,
+        }
+    }
+}
+
+/// Minimum severity of validation layer messages the debug messenger forwards to `log`,
+/// ordered from most to least severe; each variant also admits every variant above it (e.g.
+/// `Info` also lets `Warning` and `Error` through) so raising it surfaces more detail for deep
+/// debugging rather than less.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugSeverity {
+    Error,
+    Warning,
+    Info,
+    Verbose,
+}
+
+impl DebugSeverity {
+    fn message_severity(self) -> vk::instance::debug::DebugUtilsMessageSeverity {
+        use vk::instance::debug::DebugUtilsMessageSeverity;
+        let mut severity = DebugUtilsMessageSeverity::ERROR;
+        if self >= DebugSeverity::Warning {
+            severity |= DebugUtilsMessageSeverity::WARNING;
+        }
+        if self >= DebugSeverity::Info {
+            severity |= DebugUtilsMessageSeverity::INFO;
+        }
+        if self >= DebugSeverity::Verbose {
+            severity |= DebugUtilsMessageSeverity::VERBOSE;
+        }
+        severity
+    }
+}
+
 #[derive(Debug)]
 pub struct Queues {
     pub graphics: Arc<vk::device::Queue>,
     pub compute: Option<Arc<vk::device::Queue>>,
 }
 
+/// Queue family indices picked for the logical device.
+///
+/// `compute` points at a dedicated async-compute family when the physical device exposes
+/// one, otherwise it falls back to `graphics` (every `GRAPHICS` family also supports
+/// `COMPUTE`, so a compute queue is always available, just not always a distinct one).
+#[derive(Debug, Clone, Copy)]
+struct QueueFamilyIndices {
+    graphics: u32,
+    compute: u32,
+}
+
 #[derive(Debug)]
 pub struct VulkanEnvironment {
     pub instance: Arc<vk::instance::Instance>,
@@ -17,21 +79,37 @@ pub struct VulkanEnvironment {
     pub queues: Queues,
     pub memory_allocator: vk::memory::allocator::GenericMemoryAllocator<Arc<vk::memory::allocator::FreeListAllocator>>,
     pub command_buffer_allocator: vk::command_buffer::allocator::StandardCommandBufferAllocator,
+    pub descriptor_set_allocator: vk::descriptor_set::allocator::StandardDescriptorSetAllocator,
     pub window: Arc<winit::window::Window>,
     pub surface: Arc<vk::swapchain::Surface>,
+    /// Kept alive for as long as the instance; `None` unless validation was requested.
+    pub debug_messenger: Option<Arc<vk::instance::debug::DebugUtilsMessenger>>,
 }
 
 impl VulkanEnvironment {
     pub fn new(event_loop: &winit::event_loop::EventLoop<()>) -> Result<Self> {
+        Self::new_with_options(event_loop, VulkanOptions::default())
+    }
+    pub fn new_with_options(event_loop: &winit::event_loop::EventLoop<()>, options: VulkanOptions) -> Result<Self> {
         let library = vk::VulkanLibrary::new()?;
-        let required_extensions = vulkano_win::required_extensions(&library);
+        let mut enabled_extensions = vulkano_win::required_extensions(&library);
+        let mut enabled_layers = Vec::new();
+        if options.validation {
+            enabled_extensions.ext_debug_utils = true;
+            enabled_layers.push("VK_LAYER_KHRONOS_validation".to_owned());
+        }
         let instance = vk::instance::Instance::new(
             library,
             vk::instance::InstanceCreateInfo {
-                enabled_extensions: required_extensions,
+                enabled_extensions,
+                enabled_layers,
                 ..Default::default()
             },
         )?;
+        let debug_messenger = options
+            .validation
+            .then(|| Self::new_debug_messenger(&instance, options.min_severity))
+            .transpose()?;
         let (window, surface) = Self::new_window(event_loop, &instance)?;
 
         let device_extensions = vk::device::DeviceExtensions {
@@ -39,22 +117,19 @@ impl VulkanEnvironment {
             ..Default::default()
         };
 
-        let (physical_device, queue_family_index) = Self::new_physical_device(
-            &instance, 
+        let (physical_device, queue_families) = Self::new_physical_device(
+            &instance,
             &surface,
             &device_extensions
         )?;
         let (logical_device, queues) = Self::new_logical_device(
-            physical_device.clone(), 
-            queue_family_index,
+            physical_device.clone(),
+            queue_families,
             device_extensions,
         )?;
-        let queues = Queues { 
-            graphics: queues.first().cloned().ok_or_else(|| anyhow::anyhow!("failed to find graphics queue"))?, 
-            compute: None
-        };
         let memory_allocator = vk::memory::allocator::StandardMemoryAllocator::new_default(logical_device.clone());
         let command_memory_allocator = vk::command_buffer::allocator::StandardCommandBufferAllocator::new(logical_device.clone(), Default::default());
+        let descriptor_set_allocator = vk::descriptor_set::allocator::StandardDescriptorSetAllocator::new(logical_device.clone());
 
         Ok(VulkanEnvironment {
             instance,
@@ -63,10 +138,50 @@ impl VulkanEnvironment {
             queues,
             memory_allocator,
             command_buffer_allocator: command_memory_allocator,
+            descriptor_set_allocator,
             window,
             surface,
+            debug_messenger,
         })
     }
+    /// Registers a debug utils messenger that forwards validation layer output to `log`.
+    ///
+    /// Only meaningful once `ext_debug_utils` and `VK_LAYER_KHRONOS_validation` have been
+    /// enabled on the instance; the messenger filters out anything below `min_severity`.
+    fn new_debug_messenger(instance: &Arc<vk::instance::Instance>, min_severity: DebugSeverity) -> Result<Arc<vk::instance::debug::DebugUtilsMessenger>> {
+        use vk::instance::debug::{DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessengerCreateInfo};
+        unsafe {
+            vk::instance::debug::DebugUtilsMessenger::new(
+                instance.clone(),
+                DebugUtilsMessengerCreateInfo {
+                    message_severity: min_severity.message_severity(),
+                    message_type: DebugUtilsMessageType::GENERAL
+                        | DebugUtilsMessageType::VALIDATION
+                        | DebugUtilsMessageType::PERFORMANCE,
+                    ..DebugUtilsMessengerCreateInfo::user_callback(Arc::new(|msg| {
+                        let severity = if msg.severity.intersects(DebugUtilsMessageSeverity::ERROR) {
+                            log::Level::Error
+                        } else if msg.severity.intersects(DebugUtilsMessageSeverity::WARNING) {
+                            log::Level::Warn
+                        } else if msg.severity.intersects(DebugUtilsMessageSeverity::INFO) {
+                            log::Level::Info
+                        } else {
+                            log::Level::Trace
+                        };
+                        let ty = if msg.ty.intersects(DebugUtilsMessageType::VALIDATION) {
+                            "validation"
+                        } else if msg.ty.intersects(DebugUtilsMessageType::PERFORMANCE) {
+                            "performance"
+                        } else {
+                            "general"
+                        };
+                        log::log!(severity, "[{}] {}: {}", ty, msg.layer_prefix.unwrap_or("vulkan"), msg.description);
+                    }))
+                },
+            )
+        }
+        .map_err(Into::into)
+    }
     fn new_window(event_loop: &winit::event_loop::EventLoop<()>, instance: &Arc<vk::instance::Instance>) -> Result<(Arc<winit::window::Window>, Arc<vk::swapchain::Surface>)> {
         use vulkano_win::VkSurfaceBuild;
         let surface = winit::window::WindowBuilder::new()
@@ -85,13 +200,14 @@ impl VulkanEnvironment {
         instance: &Arc<vk::instance::Instance>,
         surface: &vk::swapchain::Surface,
         required_extensions: &vk::device::DeviceExtensions,
-    ) -> Result<(Arc<vk::device::physical::PhysicalDevice>, u32)> {
+    ) -> Result<(Arc<vk::device::physical::PhysicalDevice>, QueueFamilyIndices)> {
         use vk::device::physical::PhysicalDeviceType;
         instance
             .enumerate_physical_devices()?
             .filter(|p| p.supported_extensions().contains(required_extensions))
             .filter_map(|p| {
-                p.queue_family_properties()
+                let families = p.queue_family_properties();
+                let graphics = families
                     .iter()
                     .enumerate()
                     // Find the first first queue family that is suitable.
@@ -100,8 +216,20 @@ impl VulkanEnvironment {
                     .position(|(i, q)| {
                         q.queue_flags.contains(vk::device::QueueFlags::GRAPHICS)
                             && p.surface_support(i as u32, surface).unwrap_or(false)
+                    })? as u32;
+                // Prefer a family that supports compute but not graphics (dedicated async
+                // compute); fall back to the graphics family, which always supports compute too.
+                let compute = families
+                    .iter()
+                    .enumerate()
+                    .position(|(i, q)| {
+                        i as u32 != graphics
+                            && q.queue_flags.contains(vk::device::QueueFlags::COMPUTE)
+                            && !q.queue_flags.contains(vk::device::QueueFlags::GRAPHICS)
                     })
-                    .map(|q| (p, q as u32))
+                    .map(|i| i as u32)
+                    .unwrap_or(graphics);
+                Some((p, QueueFamilyIndices { graphics, compute }))
             })
             .min_by_key(|(p, _)| match p.properties().device_type {
                 PhysicalDeviceType::DiscreteGpu => 0,
@@ -114,21 +242,35 @@ impl VulkanEnvironment {
     }
     fn new_logical_device(
         physical_device: Arc<vk::device::physical::PhysicalDevice>,
-        queue_family_index: u32,
+        queue_families: QueueFamilyIndices,
         device_extensions: vk::device::DeviceExtensions,
-    ) -> Result<(Arc<vk::device::Device>, Vec<Arc<vk::device::Queue>>)> {
-        let (logical_device, queues) = vk::device::Device::new(
+    ) -> Result<(Arc<vk::device::Device>, Queues)> {
+        let dedicated_compute = queue_families.compute != queue_families.graphics;
+        let mut queue_create_infos = vec![vk::device::QueueCreateInfo {
+            queue_family_index: queue_families.graphics,
+            ..Default::default()
+        }];
+        if dedicated_compute {
+            queue_create_infos.push(vk::device::QueueCreateInfo {
+                queue_family_index: queue_families.compute,
+                ..Default::default()
+            });
+        }
+        let (logical_device, mut queues) = vk::device::Device::new(
             physical_device,
             vk::device::DeviceCreateInfo {
                 enabled_extensions: device_extensions,
-                queue_create_infos: vec![vk::device::QueueCreateInfo {
-                    queue_family_index,
-                    ..Default::default()
-                }],
+                queue_create_infos,
                 ..Default::default()
             },
         )?;
-        Ok((logical_device, queues.collect()))
+        let graphics = queues.next().ok_or_else(|| anyhow::anyhow!("failed to find graphics queue"))?;
+        let compute = if dedicated_compute {
+            Some(queues.next().ok_or_else(|| anyhow::anyhow!("failed to find compute queue"))?)
+        } else {
+            Some(graphics.clone())
+        };
+        Ok((logical_device, Queues { graphics, compute }))
     }
     fn new_swapchain(
         window: &Arc<winit::window::Window>,
@@ -161,5 +303,92 @@ impl VulkanEnvironment {
         )
         .map_err(|e| anyhow::anyhow!("failed to create swapchain: {}", e))
     }
+    pub fn surface_capabilities(&self) -> Result<vk::swapchain::SurfaceCapabilities> {
+        self.physical_device
+            .surface_capabilities(&self.surface, Default::default())
+            .map_err(|e| anyhow::anyhow!("failed to get surface capabilities: {}", e))
+    }
+    pub fn first_surface_format(&self) -> Result<Option<(vk::format::Format, vk::swapchain::ColorSpace)>> {
+        Ok(self
+            .physical_device
+            .surface_formats(&self.surface, Default::default())
+            .map_err(|e| anyhow::anyhow!("failed to get surface formats: {}", e))?
+            .into_iter()
+            .next())
+    }
+    pub fn dimension(&self) -> [u32; 2] {
+        self.window.inner_size().into()
+    }
+    pub fn supported_present_modes(&self) -> Result<Vec<vk::swapchain::PresentMode>> {
+        self.physical_device
+            .surface_present_modes(&self.surface)
+            .map(Iterator::collect)
+            .map_err(|e| anyhow::anyhow!("failed to get surface present modes: {}", e))
+    }
+    /// Falls back to `Fifo` (always guaranteed to be supported) if `requested` isn't.
+    pub fn validate_present_mode(&self, requested: vk::swapchain::PresentMode) -> Result<vk::swapchain::PresentMode> {
+        if self.supported_present_modes()?.contains(&requested) {
+            Ok(requested)
+        } else {
+            Ok(vk::swapchain::PresentMode::Fifo)
+        }
+    }
+    /// Picks the best supported depth format, preferring `D32_SFLOAT` and falling back to `D16_UNORM`.
+    pub fn depth_format(&self) -> Result<vk::format::Format> {
+        [vk::format::Format::D32_SFLOAT, vk::format::Format::D16_UNORM]
+            .into_iter()
+            .find(|&format| {
+                self.physical_device
+                    .format_properties(format)
+                    .map(|props| props.optimal_tiling_features.contains(vk::format::FormatFeatures::DEPTH_STENCIL_ATTACHMENT))
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| anyhow::anyhow!("no supported depth/stencil format found"))
+    }
+    /// Builds the single-subpass render pass used by `Swapchain`, with an optional depth attachment.
+    pub fn new_render_pass(&self, swapchain: &Arc<vk::swapchain::Swapchain>, depth: bool) -> Result<Arc<vk::render_pass::RenderPass>> {
+        let format = swapchain.image_format();
+        let render_pass = if depth {
+            let depth_format = self.depth_format()?;
+            vk::single_pass_renderpass!(
+                self.device.clone(),
+                attachments: {
+                    color: {
+                        load: Clear,
+                        store: Store,
+                        format: format,
+                        samples: 1,
+                    },
+                    depth: {
+                        load: Clear,
+                        store: DontCare,
+                        format: depth_format,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth}
+                }
+            )?
+        } else {
+            vk::single_pass_renderpass!(
+                self.device.clone(),
+                attachments: {
+                    color: {
+                        load: Clear,
+                        store: Store,
+                        format: format,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {}
+                }
+            )?
+        };
+        Ok(render_pass)
+    }
 }
 